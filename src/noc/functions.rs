@@ -22,94 +22,132 @@
 // OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
 use super::{ParseError, Value};
-use std::str::FromStr;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::u32;
 
-pub fn apply(name: &str, args: &[Value]) -> Result<Value, ParseError> {
+thread_local! {
+    // Canonicalized paths of imports currently being resolved, so a cycle
+    // (A imports B imports A) is rejected instead of recursing forever.
+    static IMPORTS_IN_PROGRESS: RefCell<HashSet<PathBuf>> = RefCell::new(HashSet::new());
+}
+
+/// Dispatch a function call found while parsing a NOC document.
+///
+/// `arg_spans` gives the byte-offset span of each entry in `args`, in
+/// the source being parsed, so a failing op can point `ParseError` at
+/// the offending argument rather than just naming it. `base_dir` is the
+/// directory of the file currently being parsed, used to resolve
+/// `import`'s relative paths; the top-level parse passes the process's
+/// current directory.
+pub fn apply(
+    name: &str,
+    args: &[Value],
+    arg_spans: &[Range<usize>],
+    base_dir: &Path,
+) -> Result<Value, ParseError> {
     match name {
-        "add" => add(args),
-        "subtract" => subtract(args),
-        "divide" => divide(args),
-        "multiply" => multiply(args),
-        "power" => power(args),
-        "intdiv" => intdiv(args),
-        "modulus" => modulus(args),
-        "concat" => concat(args),
-        "join" => join(args),
+        "add" => add(args, arg_spans),
+        "subtract" => subtract(args, arg_spans),
+        "divide" => divide(args, arg_spans),
+        "multiply" => multiply(args, arg_spans),
+        "power" => power(args, arg_spans),
+        "intdiv" => intdiv(args, arg_spans),
+        "modulus" => modulus(args, arg_spans),
+        "concat" => concat(args, arg_spans),
+        "join" => join(args, arg_spans),
+        "import" => import(args, arg_spans, base_dir),
+        "env" => env_lookup(args),
+        "eq" => eq(args),
+        "ne" => ne(args),
+        "lt" => lt(args),
+        "le" => le(args),
+        "gt" => gt(args),
+        "ge" => ge(args),
+        "and" => and(args),
+        "or" => or(args),
+        "not" => not(args),
+        "if" | "cond" => cond(args),
         _ => Err(error("No such function")),
     }
 }
 
-pub fn add(args: &[Value]) -> Result<Value, ParseError> {
+pub fn add(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     convert::<i64>(args)
-        .map(|(lhs, rhs)| (lhs + rhs).to_string())
-        .or_else(|_| convert::<f64>(args).map(|(lhs, rhs)| (lhs + rhs).to_string()))
-        .map_err(|_| error("Addition requires two numeric arguments"))
-        .map(Value::String)
+        .map(|(lhs, rhs)| Value::Int(lhs + rhs))
+        .or_else(|_| convert::<f64>(args).map(|(lhs, rhs)| Value::Float(lhs + rhs)))
+        .map_err(|e| numeric_error("Addition requires two numeric arguments", spans, e))
 }
 
-pub fn subtract(args: &[Value]) -> Result<Value, ParseError> {
+pub fn subtract(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     convert::<i64>(args)
-        .map(|(lhs, rhs)| (lhs - rhs).to_string())
-        .or_else(|_| convert::<f64>(args).map(|(lhs, rhs)| (lhs - rhs).to_string()))
-        .map_err(|_| error("Subtraction requires two numeric arguments"))
-        .map(Value::String)
+        .map(|(lhs, rhs)| Value::Int(lhs - rhs))
+        .or_else(|_| convert::<f64>(args).map(|(lhs, rhs)| Value::Float(lhs - rhs)))
+        .map_err(|e| numeric_error("Subtraction requires two numeric arguments", spans, e))
 }
 
-pub fn multiply(args: &[Value]) -> Result<Value, ParseError> {
+pub fn multiply(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     convert::<i64>(args)
-        .map(|(lhs, rhs)| (lhs * rhs).to_string())
-        .or_else(|_| convert::<f64>(args).map(|(lhs, rhs)| (lhs * rhs).to_string()))
-        .map_err(|_| error("Multiplication requires two numeric arguments"))
-        .map(Value::String)
+        .map(|(lhs, rhs)| Value::Int(lhs * rhs))
+        .or_else(|_| convert::<f64>(args).map(|(lhs, rhs)| Value::Float(lhs * rhs)))
+        .map_err(|e| numeric_error("Multiplication requires two numeric arguments", spans, e))
 }
 
-pub fn divide(args: &[Value]) -> Result<Value, ParseError> {
+pub fn divide(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     convert::<f64>(args)
-        .map(|(lhs, rhs)| (lhs / rhs).to_string())
-        .map_err(|_| error("Division requires two numeric arguments"))
-        .map(Value::String)
+        .map(|(lhs, rhs)| Value::Float(lhs / rhs))
+        .map_err(|e| numeric_error("Division requires two numeric arguments", spans, e))
 }
 
-pub fn power(args: &[Value]) -> Result<Value, ParseError> {
+pub fn power(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     convert::<i64>(args)
         .and_then(|(lhs, rhs)| {
             if rhs > 0 && rhs <= i64::from(u32::MAX) {
-                Ok(lhs.pow(rhs as u32).to_string())
+                Ok(Value::Int(lhs.pow(rhs as u32)))
             } else {
-                Err(())
+                Err(ConvertError::Arity)
             }
-        }).or_else(|_| convert::<f64>(args).map(|(lhs, rhs)| lhs.powf(rhs).to_string()))
-        .map_err(|_| error("Power requires two numeric arguments"))
-        .map(Value::String)
+        }).or_else(|_| convert::<f64>(args).map(|(lhs, rhs)| Value::Float(lhs.powf(rhs))))
+        .map_err(|e| numeric_error("Power requires two numeric arguments", spans, e))
 }
 
-pub fn intdiv(args: &[Value]) -> Result<Value, ParseError> {
+pub fn intdiv(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     convert::<i64>(args)
-        .map(|(lhs, rhs)| (lhs / rhs).to_string())
-        .map_err(|_| error("Integer division requires two integer arguments"))
-        .map(Value::String)
+        .map(|(lhs, rhs)| Value::Int(lhs / rhs))
+        .map_err(|e| numeric_error("Integer division requires two integer arguments", spans, e))
 }
 
-pub fn modulus(args: &[Value]) -> Result<Value, ParseError> {
+pub fn modulus(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     convert::<i64>(args)
-        .map(|(lhs, rhs)| (lhs % rhs).to_string())
-        .map_err(|_| error("Modulus requires two integer arguments"))
-        .map(Value::String)
+        .map(|(lhs, rhs)| Value::Int(lhs % rhs))
+        .map_err(|e| numeric_error("Modulus requires two integer arguments", spans, e))
 }
 
-fn concat(args: &[Value]) -> Result<Value, ParseError> {
+fn concat(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     args.iter()
-        .try_fold(String::new(), |mut s, a| {
-            a.as_str().map(|a| {
+        .enumerate()
+        .try_fold(String::new(), |mut s, (i, a)| match a.as_str() {
+            Some(a) => {
                 s.push_str(a);
-                s
-            })
+                Ok(s)
+            }
+            None => Err(i),
         }).map(Value::String)
-        .ok_or_else(|| error("Concat only takes string arguments"))
+        .map_err(|i| {
+            numeric_error(
+                "Concat only takes string arguments",
+                spans,
+                ConvertError::BadArg(i),
+            )
+        })
 }
 
-fn join(args: &[Value]) -> Result<Value, ParseError> {
+fn join(args: &[Value], spans: &[Range<usize>]) -> Result<Value, ParseError> {
     args.iter()
         .try_fold(Vec::new(), |mut v, a| {
             a.as_str().map(|a| {
@@ -119,28 +157,295 @@ fn join(args: &[Value]) -> Result<Value, ParseError> {
         }).ok_or_else(|| error("Join only takes string arguments"))
         .and_then(|strs| {
             if strs.is_empty() {
-                Err(error("Not enough arguments to join"))
+                Err(error_spanning("Not enough arguments to join", spans))
             } else {
                 Ok(Value::String(strs[1..].join(strs[0])))
             }
         })
 }
 
-fn convert<T: FromStr>(args: &[Value]) -> Result<(T, T), ()> {
-    args.get(2)
-        .map_or_else(|| Ok(()), |_| Err(()))
-        .and_then(|_| {
-            args.get(0)
-                .and_then(|lhs| args.get(1).map(|rhs| (lhs, rhs)))
-                .ok_or(())
-        }).and_then(|(lhs, rhs)| {
-            lhs.as_str()
-                .and_then(|lhs| rhs.as_str().map(|rhs| (lhs, rhs)))
-                .ok_or(())
-        }).and_then(|(lhs, rhs)| {
-            lhs.parse::<T>()
-                .and_then(|lhs| rhs.parse::<T>().map(|rhs| (lhs, rhs)))
-                .map_err(|_| ())
+/// Load another NOC file by path relative to `base_dir` (the directory
+/// of the importing file) and parse it into a `Value`. The caller is
+/// expected to splice the result into the current node the same way any
+/// other dict-valued node is merged, via the deep-merge already done by
+/// `Value::insert` when a key is assigned more than once.
+fn import(
+    args: &[Value],
+    arg_spans: &[Range<usize>],
+    base_dir: &Path,
+) -> Result<Value, ParseError> {
+    if args.len() != 1 {
+        return Err(error("Import takes a single path argument"));
+    }
+    let path_span = arg_spans.get(0).cloned();
+    let path = args[0]
+        .as_str()
+        .ok_or_else(|| error_at("Import's argument must be a string path", path_span.clone()))?;
+
+    let full_path = base_dir.join(path);
+    let canonical = full_path
+        .canonicalize()
+        .map_err(|_| error_at("Could not find the file to import", path_span.clone()))?;
+
+    let is_cycle =
+        IMPORTS_IN_PROGRESS.with(|in_progress| in_progress.borrow().contains(&canonical));
+    if is_cycle {
+        // Point at the `import` call that closed the cycle, since `reason`
+        // can't carry the canonicalized path or the chain that led here.
+        return Err(error_at(
+            "Import cycle detected: this file is already being imported",
+            path_span,
+        ));
+    }
+
+    let source =
+        fs::read_to_string(&canonical).map_err(|_| error_at("Could not read import", path_span))?;
+
+    // Nested imports inside the imported file must resolve relative to
+    // *its* directory, not `base_dir` (the importer's), so a chain of
+    // imports each resolves against its own importer.
+    let import_dir = canonical.parent().unwrap_or(base_dir);
+
+    IMPORTS_IN_PROGRESS.with(|in_progress| in_progress.borrow_mut().insert(canonical.clone()));
+    let result = Value::from_str_in(&source, import_dir).map_err(|e| {
+        // A cycle closed further down the chain reaches this level only as
+        // the nested parse's message; keep reporting it as a cycle.
+        if e.contains("Import cycle detected") {
+            error("Import cycle detected: this file is already being imported")
+        } else {
+            error("Failed to parse imported file")
+        }
+    });
+    IMPORTS_IN_PROGRESS.with(|in_progress| in_progress.borrow_mut().remove(&canonical));
+
+    result
+}
+
+/// Read an environment variable, falling back to an optional second
+/// argument when it isn't set, so configs can be parameterized by
+/// environment.
+fn env_lookup(args: &[Value]) -> Result<Value, ParseError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(error("env takes a variable name and an optional default"));
+    }
+    let name = args[0]
+        .as_str()
+        .ok_or_else(|| error("env's first argument must be a string name"))?;
+
+    match env::var(name) {
+        Ok(value) => Ok(Value::String(value)),
+        Err(_) => args
+            .get(1)
+            .cloned()
+            .ok_or_else(|| error("Environment variable not set and no default given")),
+    }
+}
+
+/// Compare two operands: native booleans compare directly (`false <
+/// true`), otherwise numerically if both parse as numbers, lexically if
+/// both are strings, following the same "numeric first" rule as
+/// arithmetic. Any other pairing (e.g. a number against a non-numeric
+/// string, or a boolean against a string) is not comparable.
+fn compare(args: &[Value]) -> Result<Ordering, ()> {
+    if args.len() != 2 {
+        return Err(());
+    }
+    match (&args[0], &args[1]) {
+        (Value::Bool(lhs), Value::Bool(rhs)) => Ok(lhs.cmp(rhs)),
+        _ => match (f64::from_value(&args[0]), f64::from_value(&args[1])) {
+            (Some(lhs), Some(rhs)) => lhs.partial_cmp(&rhs).ok_or(()),
+            _ => args[0]
+                .as_str()
+                .and_then(|lhs| args[1].as_str().map(|rhs| lhs.cmp(rhs)))
+                .ok_or(()),
+        },
+    }
+}
+
+pub fn eq(args: &[Value]) -> Result<Value, ParseError> {
+    compare(args)
+        .map(|o| Value::Bool(o == Ordering::Equal))
+        .map_err(|_| error("eq requires two comparable arguments"))
+}
+
+pub fn ne(args: &[Value]) -> Result<Value, ParseError> {
+    compare(args)
+        .map(|o| Value::Bool(o != Ordering::Equal))
+        .map_err(|_| error("ne requires two comparable arguments"))
+}
+
+pub fn lt(args: &[Value]) -> Result<Value, ParseError> {
+    compare(args)
+        .map(|o| Value::Bool(o == Ordering::Less))
+        .map_err(|_| error("lt requires two comparable arguments"))
+}
+
+pub fn le(args: &[Value]) -> Result<Value, ParseError> {
+    compare(args)
+        .map(|o| Value::Bool(o != Ordering::Greater))
+        .map_err(|_| error("le requires two comparable arguments"))
+}
+
+pub fn gt(args: &[Value]) -> Result<Value, ParseError> {
+    compare(args)
+        .map(|o| Value::Bool(o == Ordering::Greater))
+        .map_err(|_| error("gt requires two comparable arguments"))
+}
+
+pub fn ge(args: &[Value]) -> Result<Value, ParseError> {
+    compare(args)
+        .map(|o| Value::Bool(o != Ordering::Less))
+        .map_err(|_| error("ge requires two comparable arguments"))
+}
+
+/// A boolean operand: the native `Bool` variant, or the strings
+/// `"true"`/`"false"` for configs that haven't adopted native scalars.
+fn truthy(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => match s.as_str() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+pub fn and(args: &[Value]) -> Result<Value, ParseError> {
+    args.get(0)
+        .and_then(truthy)
+        .and_then(|lhs| args.get(1).and_then(truthy).map(|rhs| lhs && rhs))
+        .filter(|_| args.len() == 2)
+        .map(Value::Bool)
+        .ok_or_else(|| error("and requires two boolean arguments"))
+}
+
+pub fn or(args: &[Value]) -> Result<Value, ParseError> {
+    args.get(0)
+        .and_then(truthy)
+        .and_then(|lhs| args.get(1).and_then(truthy).map(|rhs| lhs || rhs))
+        .filter(|_| args.len() == 2)
+        .map(Value::Bool)
+        .ok_or_else(|| error("or requires two boolean arguments"))
+}
+
+pub fn not(args: &[Value]) -> Result<Value, ParseError> {
+    args.get(0)
+        .filter(|_| args.len() == 1)
+        .and_then(truthy)
+        .map(|b| Value::Bool(!b))
+        .ok_or_else(|| error("not requires one boolean argument"))
+}
+
+/// Select one of two already-parsed branches based on a condition,
+/// without re-evaluating either: `cond(condition, then, else)`.
+pub fn cond(args: &[Value]) -> Result<Value, ParseError> {
+    if args.len() != 3 {
+        return Err(error("if requires a condition, a then branch and an else branch"));
+    }
+    truthy(&args[0])
+        .map(|is_true| if is_true { args[1].clone() } else { args[2].clone() })
+        .ok_or_else(|| error("if's condition must be a boolean"))
+}
+
+impl ParseError {
+    /// Render this error as a human-readable, source-pointing message:
+    /// the offending line, a caret/underline under each span in
+    /// `positions`, and the `reason`. Falls back to a bare `reason` when
+    /// no positions were recorded.
+    pub fn render(&self, source: &str) -> String {
+        if self.positions.is_empty() {
+            return self.reason.to_owned();
+        }
+        let mut rendered = String::new();
+        for span in &self.positions {
+            let (line, column, line_text) = locate(source, span.start);
+            let end = span.end.max(span.start + 1).min(source.len());
+            let underline_width = source
+                .get(span.start..end)
+                .map_or(1, |s| s.chars().count().max(1));
+            rendered.push_str(&format!("line {}, column {}:\n", line, column));
+            rendered.push_str(line_text);
+            rendered.push('\n');
+            rendered.push_str(&" ".repeat(column - 1));
+            rendered.push_str(&"^".repeat(underline_width));
+            rendered.push('\n');
+        }
+        rendered.push_str(self.reason);
+        rendered
+    }
+}
+
+/// Map a byte position in `source` to its 1-based (line, column) and the
+/// full text of that line, for diagnostics rendering. The column is
+/// counted in characters, not bytes, so multi-byte UTF-8 text earlier on
+/// the line doesn't push the caret past the actual token.
+fn locate(source: &str, byte_pos: usize) -> (usize, usize, &str) {
+    let mut line = 1;
+    let mut line_start = 0;
+    for (i, c) in source.char_indices() {
+        if i >= byte_pos {
+            break;
+        }
+        if c == '\n' {
+            line += 1;
+            line_start = i + 1;
+        }
+    }
+    let line_end = source[line_start..]
+        .find('\n')
+        .map(|offset| line_start + offset)
+        .unwrap_or_else(|| source.len());
+    let column = source[line_start..byte_pos].chars().count() + 1;
+    (line, column, &source[line_start..line_end])
+}
+
+/// A scalar type that a `Value` can be coerced into for arithmetic: the
+/// native variant is taken directly, falling back to `str::parse` for
+/// `Value::String` so stringly-typed config keeps working.
+trait Numeric: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl Numeric for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(i) => Some(*i),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl Numeric for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(f) => Some(*f),
+            Value::Int(i) => Some(*i as f64),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Why `convert` couldn't produce a pair of operands, precise enough to
+/// let the caller point a `ParseError` at the argument that broke.
+enum ConvertError {
+    Arity,
+    BadArg(usize),
+}
+
+fn convert<T: Numeric>(args: &[Value]) -> Result<(T, T), ConvertError> {
+    if args.len() != 2 {
+        return Err(ConvertError::Arity);
+    }
+    T::from_value(&args[0])
+        .ok_or(ConvertError::BadArg(0))
+        .and_then(|lhs| {
+            T::from_value(&args[1])
+                .ok_or(ConvertError::BadArg(1))
+                .map(|rhs| (lhs, rhs))
         })
 }
 
@@ -150,3 +455,218 @@ fn error(reason: &'static str) -> ParseError {
         positions: Vec::new(),
     }
 }
+
+/// Build a `ParseError` pointing at a single span, if one is known.
+fn error_at(reason: &'static str, span: Option<Range<usize>>) -> ParseError {
+    ParseError {
+        reason,
+        positions: span.into_iter().collect(),
+    }
+}
+
+/// Build a `ParseError` pointing at the span of the argument `convert`
+/// blamed, if any; arity mismatches carry no useful single position.
+fn numeric_error(reason: &'static str, spans: &[Range<usize>], err: ConvertError) -> ParseError {
+    let positions = match err {
+        ConvertError::BadArg(i) => spans.get(i).cloned().into_iter().collect(),
+        ConvertError::Arity => Vec::new(),
+    };
+    ParseError { reason, positions }
+}
+
+/// Build a `ParseError` pointing at the span of the whole call, taken as
+/// the union of every argument's span.
+fn error_spanning(reason: &'static str, spans: &[Range<usize>]) -> ParseError {
+    let positions = match (
+        spans.iter().map(|s| s.start).min(),
+        spans.iter().map(|s| s.end).max(),
+    ) {
+        (Some(start), Some(end)) => vec![start..end],
+        _ => Vec::new(),
+    };
+    ParseError { reason, positions }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_divide_points_at_non_numeric_operand() {
+        let args = vec![Value::Int(10), Value::String("x".to_owned())];
+        let spans = vec![0..2, 6..9];
+        let err = divide(&args, &spans).unwrap_err();
+        assert_eq!(err.positions, vec![6..9]);
+    }
+
+    #[test]
+    fn test_divide_numeric_ok() {
+        let args = vec![Value::Int(10), Value::Int(4)];
+        let result = divide(&args, &[0..2, 3..4]).unwrap();
+        assert_eq!(result, Value::Float(2.5));
+    }
+
+    #[test]
+    fn test_join_too_few_args_points_at_the_call() {
+        let args = vec![];
+        let spans: Vec<Range<usize>> = vec![];
+        let err = join(&args, &spans).unwrap_err();
+        assert!(err.positions.is_empty());
+
+        let args = vec![Value::String("x".to_owned())];
+        let spans = vec![4..5];
+        let err = join(&args, &spans).unwrap_err();
+        assert_eq!(err.positions, vec![4..5]);
+    }
+
+    #[test]
+    fn test_concat_points_at_non_string_operand() {
+        let args = vec![Value::String("a".to_owned()), Value::Int(2)];
+        let spans = vec![0..1, 4..5];
+        let err = concat(&args, &spans).unwrap_err();
+        assert_eq!(err.positions, vec![4..5]);
+    }
+
+    #[test]
+    fn test_concat_strings_ok() {
+        let args = vec![Value::String("a".to_owned()), Value::String("b".to_owned())];
+        let result = concat(&args, &[0..1, 2..3]).unwrap();
+        assert_eq!(result, Value::String("ab".to_owned()));
+    }
+
+    #[test]
+    fn test_render_points_at_utf8_span() {
+        let source = "b\u{3b2} (divide 1 \"x\")\n";
+        let x_byte_pos = source.find('x').unwrap();
+        let err = ParseError {
+            reason: "Division requires two numeric arguments",
+            positions: vec![x_byte_pos..x_byte_pos + 1],
+        };
+        let rendered = err.render(source);
+        let caret_line = rendered.lines().nth(2).unwrap();
+        let x_column = source[..x_byte_pos].chars().count();
+        assert_eq!(caret_line.find('^'), Some(x_column));
+    }
+
+    #[test]
+    fn test_compare_numeric_lexical_and_bool() {
+        assert_eq!(
+            eq(&[Value::Int(6), Value::String("6".to_owned())]).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            lt(&[
+                Value::String("a".to_owned()),
+                Value::String("b".to_owned())
+            ])
+            .unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eq(&[Value::Bool(true), Value::Bool(true)]).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            lt(&[Value::Bool(false), Value::Bool(true)]).unwrap(),
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn test_logical_functions() {
+        assert_eq!(
+            and(&[Value::Bool(true), Value::Bool(false)]).unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            or(&[Value::Bool(true), Value::Bool(false)]).unwrap(),
+            Value::Bool(true)
+        );
+        assert_eq!(not(&[Value::Bool(false)]).unwrap(), Value::Bool(true));
+    }
+
+    #[test]
+    fn test_cond_selects_branch() {
+        let args = vec![Value::Bool(true), Value::Int(1), Value::Int(2)];
+        assert_eq!(cond(&args).unwrap(), Value::Int(1));
+
+        let args = vec![Value::Bool(false), Value::Int(1), Value::Int(2)];
+        assert_eq!(cond(&args).unwrap(), Value::Int(2));
+    }
+
+    /// A scratch directory under the OS temp dir, torn down on drop, for
+    /// tests that need real files on disk to exercise `import`.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("nereon_functions_test_{}", name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn write(&self, rel_path: &str, contents: &str) {
+            let path = self.0.join(rel_path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            fs::write(path, contents).unwrap();
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_import_resolves_nested_import_against_its_own_directory() {
+        let dir = TempDir::new("nested_import");
+        dir.write("entry.noc", r#"x (import "sub/mid.noc")"#);
+        dir.write("sub/mid.noc", r#"y (import "leaf.noc")"#);
+        dir.write("sub/leaf.noc", r#"z "ok""#);
+
+        let args = vec![Value::String("entry.noc".to_owned())];
+        let result = import(&args, &[0..0], &dir.0).unwrap();
+
+        let mid = result.as_dict().and_then(|d| d.get("x")).unwrap();
+        let leaf = mid.as_dict().and_then(|d| d.get("y")).unwrap();
+        assert_eq!(leaf.get::<String>("z"), Ok("ok".to_owned()));
+    }
+
+    #[test]
+    fn test_import_detects_cycle() {
+        let dir = TempDir::new("import_cycle");
+        dir.write("a.noc", r#"x (import "b.noc")"#);
+        dir.write("b.noc", r#"y (import "a.noc")"#);
+
+        let args = vec![Value::String("a.noc".to_owned())];
+        let err = import(&args, &[0..0], &dir.0).unwrap_err();
+        assert!(err.reason.contains("cycle"));
+    }
+
+    #[test]
+    fn test_env_lookup_present_and_absent() {
+        env::set_var("NEREON_TEST_ENV_LOOKUP", "from-env");
+        let args = vec![Value::String("NEREON_TEST_ENV_LOOKUP".to_owned())];
+        assert_eq!(
+            env_lookup(&args).unwrap(),
+            Value::String("from-env".to_owned())
+        );
+        env::remove_var("NEREON_TEST_ENV_LOOKUP");
+
+        let args = vec![
+            Value::String("NEREON_TEST_ENV_LOOKUP".to_owned()),
+            Value::String("fallback".to_owned()),
+        ];
+        assert_eq!(
+            env_lookup(&args).unwrap(),
+            Value::String("fallback".to_owned())
+        );
+
+        let args = vec![Value::String("NEREON_TEST_ENV_LOOKUP".to_owned())];
+        assert!(env_lookup(&args).is_err());
+    }
+}
@@ -23,11 +23,15 @@
 
 use std::collections::{BTreeMap, HashMap};
 use std::iter::{self, FromIterator};
+use std::path::Path;
 use std::str::FromStr;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
     Dict(HashMap<String, Value>),
     List(Vec<Value>),
 }
@@ -44,6 +48,24 @@ impl From<String> for Value {
     }
 }
 
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Float(f)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
 impl From<HashMap<String, Value>> for Value {
     fn from(m: HashMap<String, Value>) -> Self {
         Value::Dict(m)
@@ -129,6 +151,48 @@ impl Value {
         }
     }
 
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn is_int(&self) -> bool {
+        match self {
+            Value::Int(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        match self {
+            Value::Float(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn is_bool(&self) -> bool {
+        match self {
+            Value::Bool(_) => true,
+            _ => false,
+        }
+    }
+
     pub fn as_dict<'a>(&'a self) -> Option<&'a HashMap<String, Value>> {
         match self {
             Value::Dict(ref map) => Some(map),
@@ -174,13 +238,18 @@ impl Value {
     pub fn as_noc_string(&self) -> String {
         match self {
             Value::String(s) => format!("\"{}\"", s),
+            Value::Int(i) => i.to_string(),
+            Value::Float(f) => f.to_string(),
+            Value::Bool(b) => b.to_string(),
             Value::List(v) => {
                 let values = v
                     .iter()
                     .map(|v| match v {
                         Value::Dict(_) => format!("{{{}}}", v.as_noc_string()),
                         Value::List(_) => format!("[{}]", v.as_noc_string()),
-                        Value::String(_) => v.as_noc_string(),
+                        Value::String(_) | Value::Int(_) | Value::Float(_) | Value::Bool(_) => {
+                            v.as_noc_string()
+                        }
                     })
                     .collect::<Vec<_>>();
                 values.join(",")
@@ -191,7 +260,9 @@ impl Value {
                     .map(|(k, v)| match v {
                         Value::Dict(_) => format!("\"{}\" {{{}}}", k, v.as_noc_string()),
                         Value::List(_) => format!("\"{}\" [{}]", k, v.as_noc_string()),
-                        Value::String(_) => format!("\"{}\" {}", k, v.as_noc_string()),
+                        Value::String(_) | Value::Int(_) | Value::Float(_) | Value::Bool(_) => {
+                            format!("\"{}\" {}", k, v.as_noc_string())
+                        }
                     })
                     .collect::<Vec<_>>();
                 values.join(",")
@@ -207,6 +278,9 @@ impl Value {
         let tabs = iter::repeat('\t').take(indent).collect::<String>();
         match self {
             Value::String(s) => format!("\"{}\"", s),
+            Value::Int(i) => i.to_string(),
+            Value::Float(f) => f.to_string(),
+            Value::Bool(b) => b.to_string(),
             Value::List(v) => v
                 .iter()
                 .map(|v| {
@@ -214,7 +288,9 @@ impl Value {
                     match v {
                         Value::Dict(_) => format!("{}{{\n{}\n{}}}", tabs, s, tabs),
                         Value::List(_) => format!("{}[\n{}\n{}]", tabs, s, tabs),
-                        Value::String(_) => format!("{}{}", tabs, s),
+                        Value::String(_) | Value::Int(_) | Value::Float(_) | Value::Bool(_) => {
+                            format!("{}{}", tabs, s)
+                        }
                     }
                 })
                 .collect::<Vec<_>>()
@@ -226,7 +302,9 @@ impl Value {
                     match v {
                         Value::Dict(_) => format!("{}\"{}\" {{\n{}\n{}}}", tabs, k, s, tabs),
                         Value::List(_) => format!("{}\"{}\" [\n{}\n{}]", tabs, k, s, tabs),
-                        Value::String(_) => format!("{}\"{}\" {}", tabs, k, s),
+                        Value::String(_) | Value::Int(_) | Value::Float(_) | Value::Bool(_) => {
+                            format!("{}\"{}\" {}", tabs, k, s)
+                        }
                     }
                 })
                 .collect::<Vec<_>>()
@@ -235,6 +313,175 @@ impl Value {
     }
 }
 
+/// A declarative description of the shape a `Value` tree is expected to
+/// have, checked with `Value::validate`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Schema {
+    Str,
+    Int,
+    Float,
+    Bool,
+    List(Box<Schema>),
+    Dict(DictSchema),
+    OneOf(Vec<Schema>),
+}
+
+/// The shape of a `Schema::Dict`: its known fields, and whether keys not
+/// named in `fields` are passed through unchanged or rejected.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DictSchema {
+    pub fields: HashMap<String, Field>,
+    pub allow_unknown: bool,
+}
+
+/// A single entry in a `Schema::Dict`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Field {
+    pub required: bool,
+    pub schema: Schema,
+    pub default: Option<Value>,
+}
+
+/// One problem found while validating a `Value` against a `Schema`,
+/// naming the dotted key path (e.g. `servers.0.port`) of the offending
+/// node so a config author can locate it without a bare message.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SchemaError {
+    pub path: String,
+    pub reason: String,
+}
+
+impl SchemaError {
+    fn new<S: Into<String>>(path: &str, reason: S) -> Self {
+        SchemaError {
+            path: path.to_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+fn child_path(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_owned()
+    } else {
+        format!("{}.{}", path, segment)
+    }
+}
+
+impl Value {
+    /// Validate this value against `schema`, returning a copy with
+    /// missing optional fields filled in from their defaults on success,
+    /// or every problem found on failure. Errors accumulate rather than
+    /// short-circuiting, so a single pass reports every broken key.
+    /// Unknown dict keys not named in the schema are passed through
+    /// unchanged when `DictSchema::allow_unknown` is set, rejected
+    /// otherwise.
+    pub fn validate(&self, schema: &Schema) -> Result<Value, Vec<SchemaError>> {
+        self.validate_at("", schema)
+    }
+
+    fn validate_at(&self, path: &str, schema: &Schema) -> Result<Value, Vec<SchemaError>> {
+        match schema {
+            Schema::Str => match self {
+                Value::String(_) => Ok(self.clone()),
+                _ => Err(vec![SchemaError::new(path, "expected a string")]),
+            },
+            Schema::Int => match self {
+                Value::Int(_) => Ok(self.clone()),
+                Value::String(s) => s
+                    .parse::<i64>()
+                    .map(Value::Int)
+                    .map_err(|_| vec![SchemaError::new(path, "expected an integer")]),
+                _ => Err(vec![SchemaError::new(path, "expected an integer")]),
+            },
+            Schema::Float => match self {
+                Value::Float(_) => Ok(self.clone()),
+                Value::Int(i) => Ok(Value::Float(*i as f64)),
+                Value::String(s) => s
+                    .parse::<f64>()
+                    .map(Value::Float)
+                    .map_err(|_| vec![SchemaError::new(path, "expected a float")]),
+                _ => Err(vec![SchemaError::new(path, "expected a float")]),
+            },
+            Schema::Bool => match self {
+                Value::Bool(_) => Ok(self.clone()),
+                Value::String(s) => s
+                    .parse::<bool>()
+                    .map(Value::Bool)
+                    .map_err(|_| vec![SchemaError::new(path, "expected a bool")]),
+                _ => Err(vec![SchemaError::new(path, "expected a bool")]),
+            },
+            Schema::List(element) => match self {
+                Value::List(items) => {
+                    let mut validated = Vec::with_capacity(items.len());
+                    let mut errors = Vec::new();
+                    for (i, item) in items.iter().enumerate() {
+                        match item.validate_at(&child_path(path, &i.to_string()), element) {
+                            Ok(v) => validated.push(v),
+                            Err(mut e) => errors.append(&mut e),
+                        }
+                    }
+                    if errors.is_empty() {
+                        Ok(Value::List(validated))
+                    } else {
+                        Err(errors)
+                    }
+                }
+                _ => Err(vec![SchemaError::new(path, "expected a list")]),
+            },
+            Schema::Dict(dict_schema) => match self.as_dict() {
+                Some(map) => {
+                    let mut validated = map.clone();
+                    let mut errors = Vec::new();
+                    for (name, field) in &dict_schema.fields {
+                        let field_path = child_path(path, name);
+                        match map.get(name) {
+                            Some(value) => match value.validate_at(&field_path, &field.schema) {
+                                Ok(v) => {
+                                    validated.insert(name.to_owned(), v);
+                                }
+                                Err(mut e) => errors.append(&mut e),
+                            },
+                            None => {
+                                if field.required {
+                                    errors.push(SchemaError::new(&field_path, "missing required field"));
+                                } else if let Some(default) = &field.default {
+                                    validated.insert(name.to_owned(), default.clone());
+                                }
+                            }
+                        }
+                    }
+                    if !dict_schema.allow_unknown {
+                        for name in map.keys() {
+                            if !dict_schema.fields.contains_key(name) {
+                                errors.push(SchemaError::new(&child_path(path, name), "unknown field"));
+                                validated.remove(name);
+                            }
+                        }
+                    }
+                    if errors.is_empty() {
+                        Ok(Value::Dict(validated))
+                    } else {
+                        Err(errors)
+                    }
+                }
+                None => Err(vec![SchemaError::new(path, "expected a dict")]),
+            },
+            Schema::OneOf(schemas) => {
+                for candidate in schemas {
+                    if let Ok(v) = self.validate_at(path, candidate) {
+                        return Ok(v);
+                    }
+                }
+                Err(vec![SchemaError::new(
+                    path,
+                    "value did not match any schema in oneOf",
+                )])
+            }
+        }
+    }
+}
+
 impl FromStr for Value {
     type Err = String;
 
@@ -243,6 +490,18 @@ impl FromStr for Value {
     }
 }
 
+impl Value {
+    /// Parse `input` as NOC the same way `Value::from_str` does, except
+    /// any nested `import` calls it contains are resolved relative to
+    /// `base_dir` instead of the process's current directory. `import`
+    /// uses this (with the importing file's own directory) so that a
+    /// chain of imports resolves each hop against its importer, not the
+    /// top-level caller.
+    pub fn from_str_in(input: &str, base_dir: &Path) -> Result<Value, String> {
+        super::parse_in(input, base_dir)
+    }
+}
+
 pub trait FromValue<OK = Self> {
     fn from_value(value: &Value) -> Result<OK, String>;
     // this is a kludge so Value::get::<Option<T>> can work
@@ -260,6 +519,43 @@ impl FromValue for String {
     }
 }
 
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Int(i) => Ok(*i),
+            Value::String(s) => s
+                .parse::<i64>()
+                .map_err(|_| "Value is not an integer".to_owned()),
+            _ => Err("Value is not an integer".to_owned()),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Float(f) => Ok(*f),
+            Value::Int(i) => Ok(*i as f64),
+            Value::String(s) => s
+                .parse::<f64>()
+                .map_err(|_| "Value is not a float".to_owned()),
+            _ => Err("Value is not a float".to_owned()),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Bool(b) => Ok(*b),
+            Value::String(s) => s
+                .parse::<bool>()
+                .map_err(|_| "Value is not a bool".to_owned()),
+            _ => Err("Value is not a bool".to_owned()),
+        }
+    }
+}
+
 impl<T> FromValue for Option<T>
 where
     T: FromValue,
@@ -293,7 +589,7 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::Value;
+    use super::{DictSchema, Field, Schema, Value};
     use std::collections::HashMap;
     use std::str::FromStr;
 
@@ -335,4 +631,89 @@ mod tests {
             Ok(HashMap::new())
         );
     }
+
+    #[test]
+    fn test_value_scalar_from_native() {
+        assert_eq!(Value::from(6i64), Value::Int(6));
+        assert_eq!(Value::from(1.5f64), Value::Float(1.5));
+        assert_eq!(Value::from(true), Value::Bool(true));
+        assert_eq!(Value::Int(6).as_noc_string(), "6");
+        assert_eq!(Value::Bool(false).as_noc_string(), "false");
+    }
+
+    #[test]
+    fn test_value_scalar_get() {
+        let mut value = Value::from(HashMap::new());
+        value.insert(vec!["a"], 6i64);
+        value.insert(vec!["b"], true);
+        assert_eq!(value.get("a"), Ok(6i64));
+        assert_eq!(value.get("b"), Ok(true));
+        // string fallback still parses
+        let value = Value::from_str(r#"a "6", b "true""#).unwrap();
+        assert_eq!(value.get("a"), Ok(6i64));
+        assert_eq!(value.get("b"), Ok(true));
+    }
+
+    #[test]
+    fn test_value_validate() {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "port".to_owned(),
+            Field {
+                required: true,
+                schema: Schema::Int,
+                default: None,
+            },
+        );
+        fields.insert(
+            "host".to_owned(),
+            Field {
+                required: false,
+                schema: Schema::Str,
+                default: Some(Value::from("localhost")),
+            },
+        );
+        let schema = Schema::Dict(DictSchema {
+            fields,
+            allow_unknown: true,
+        });
+
+        let value = Value::from_str(r#"port "8080""#).unwrap();
+        let validated = value.validate(&schema).unwrap();
+        assert_eq!(validated.get("port"), Ok(8080i64));
+        assert_eq!(validated.get("host"), Ok("localhost".to_owned()));
+
+        let missing = Value::from_str(r#"host "example.com""#).unwrap();
+        let errors = missing.validate(&schema).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "port");
+
+        // an unrecognized key is passed through when allow_unknown is set
+        let with_extra = Value::from_str(r#"port "8080", extra "x""#).unwrap();
+        let validated = with_extra.validate(&schema).unwrap();
+        assert_eq!(validated.get("extra"), Ok("x".to_owned()));
+    }
+
+    #[test]
+    fn test_value_validate_rejects_unknown_keys() {
+        let mut fields = HashMap::new();
+        fields.insert(
+            "port".to_owned(),
+            Field {
+                required: true,
+                schema: Schema::Int,
+                default: None,
+            },
+        );
+        let schema = Schema::Dict(DictSchema {
+            fields,
+            allow_unknown: false,
+        });
+
+        let value = Value::from_str(r#"port "8080", extra "x""#).unwrap();
+        let errors = value.validate(&schema).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, "extra");
+        assert_eq!(errors[0].reason, "unknown field");
+    }
 }